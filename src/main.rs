@@ -1,28 +1,52 @@
+use command_group::{CommandGroup, GroupChild};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use notify::Watcher;
-use std::{env, path::PathBuf, sync::mpsc, time::Duration};
+use std::{env, path::Path, path::PathBuf, sync::mpsc, time::Duration};
 
 fn main() {
     let settings = parse_arguments();
 
     // Set up the filesystem watcher. Events will be send back over the `event_rx` channel receiver.
+    // `--poll` swaps in `PollWatcher`, a reliable fallback where the OS-native backend
+    // (inotify/FSEvents/ReadDirectoryChanges) doesn't work, e.g. network filesystems.
     let (event_tx, event_rx) = mpsc::channel();
-    let mut watcher = notify::watcher(event_tx, settings.delay).unwrap();
+    let mut watcher = match settings.poll_interval {
+        Some(interval) => AnyWatcher::Poll(notify::PollWatcher::new(event_tx, interval).unwrap()),
+        None => AnyWatcher::Native(notify::watcher(event_tx, settings.delay).unwrap()),
+    };
     watcher
-        .watch(settings.watch_path, notify::RecursiveMode::Recursive)
+        .watch(&settings.watch_path, notify::RecursiveMode::Recursive)
         .unwrap();
 
-    let events_listened_for = &settings.events;
+    // Holds the currently-running trailing-command child when `--restart` is set, so a new
+    // matching event can terminate it (and its whole process group) before starting a fresh one.
+    let mut running_child: Option<GroupChild> = None;
 
-    for event in event_rx {
+    for event in &event_rx {
         use notify::DebouncedEvent::*;
         match event {
             NoticeWrite(_path) => (),
             NoticeRemove(_path) => (),
-            Create(path) => if events_listened_for.contains(&Events::Create) {println!("create {}", path.display())},
-            Write(path) => if events_listened_for.contains(&Events::Write) {println!("write {}", path.display())},
-            Chmod(path) => if events_listened_for.contains(&Events::Chmod) {println!("chmod {}", path.display())},
-            Remove(path) => if events_listened_for.contains(&Events::Remove) {println!("remove {}", path.display())},
-            Rename(from, to) => if events_listened_for.contains(&Events::Rename) {println!("rename {} => {}", from.display(), to.display())},
+            Create(path) => if should_act(&settings, &path, Events::Create) {
+                begin_action(&settings, &event_rx);
+                handle_event(&settings, &mut running_child, "create", &path, None)
+            },
+            Write(path) => if should_act(&settings, &path, Events::Write) {
+                begin_action(&settings, &event_rx);
+                handle_event(&settings, &mut running_child, "write", &path, None)
+            },
+            Chmod(path) => if should_act(&settings, &path, Events::Chmod) {
+                begin_action(&settings, &event_rx);
+                handle_event(&settings, &mut running_child, "chmod", &path, None)
+            },
+            Remove(path) => if should_act(&settings, &path, Events::Remove) {
+                begin_action(&settings, &event_rx);
+                handle_event(&settings, &mut running_child, "remove", &path, None)
+            },
+            Rename(from, to) => if should_act(&settings, &to, Events::Rename) {
+                begin_action(&settings, &event_rx);
+                handle_event(&settings, &mut running_child, "rename", &to, Some(&from))
+            },
             Rescan => (),
             Error(error, None) => eprintln!("error: {}", error),
             Error(error, Some(path)) => eprintln!("error at {}: {}", path.display(), error),
@@ -34,6 +58,161 @@ fn main() {
     ::std::process::exit(1);
 }
 
+/// Checks `path` against the configured ignore matcher (`.gitignore` files plus `--ignore`
+/// globs), so generated/build artifacts don't spam the watcher.
+fn is_ignored(settings: &Settings, path: &Path) -> bool {
+    settings.ignore_matcher.is_ignored(path, path.is_dir())
+}
+
+/// Whether `event` should be reported/acted on: its kind must be one the user asked for, its
+/// path must not be ignored, and it must pass the `--exts`/`--filter` path filter, if any.
+fn should_act(settings: &Settings, path: &Path, event: Events) -> bool {
+    settings.events.contains(&event)
+        && !is_ignored(settings, path)
+        && match &settings.path_filter {
+            Some(filter) => filter.is_match(path),
+            None => true,
+        }
+}
+
+/// Drains any further events already queued up within the delay window, collapsing a burst of
+/// changes (e.g. an editor writing many files at once) into a single action, then clears the
+/// screen first if `--clear` was given.
+fn begin_action(settings: &Settings, event_rx: &mpsc::Receiver<notify::DebouncedEvent>) {
+    let deadline = std::time::Instant::now() + settings.delay;
+    loop {
+        match event_rx.try_recv() {
+            Ok(_) => continue,
+            Err(mpsc::TryRecvError::Disconnected) => break,
+            Err(mpsc::TryRecvError::Empty) => {
+                if std::time::Instant::now() >= deadline {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+
+    if settings.clear_screen {
+        if let Err(error) = clearscreen::clear() {
+            eprintln!("failed to clear screen: {}", error);
+        }
+    }
+}
+
+/// Either prints the event (the default) or, when a trailing `-- CMD` was given, runs it with
+/// `$WATCHED_PATH` and `$WATCHED_EVENT` set on the child's environment. `from` is only used for
+/// the default rename message (`rename {from} => {path}`).
+fn handle_event(
+    settings: &Settings,
+    running_child: &mut Option<GroupChild>,
+    event_name: &str,
+    path: &PathBuf,
+    from: Option<&PathBuf>,
+) {
+    match &settings.command {
+        Some(command) if settings.restart => {
+            restart_command(command, running_child, path, event_name)
+        }
+        Some(command) => run_command(command, path, event_name),
+        None => match from {
+            Some(from) => println!("rename {} => {}", from.display(), path.display()),
+            None => println!("{} {}", event_name, path.display()),
+        },
+    }
+}
+
+/// Spawns `command`, waits for it to finish, and reports its exit status on stderr.
+fn run_command(command: &[String], path: &PathBuf, event_name: &str) {
+    let status = std::process::Command::new(&command[0])
+        .args(&command[1..])
+        .env("WATCHED_PATH", path)
+        .env("WATCHED_EVENT", event_name)
+        .status();
+
+    match status {
+        Ok(status) => eprintln!("command exited with {}", status),
+        Err(error) => eprintln!("failed to run command: {}", error),
+    }
+}
+
+/// Kills `running_child` (and its whole process group, so e.g. a shell's spawned server doesn't
+/// survive as an orphan) if it's still alive, then spawns `command` afresh in its own process
+/// group without waiting for it, storing the new handle back in `running_child`.
+fn restart_command(
+    command: &[String],
+    running_child: &mut Option<GroupChild>,
+    path: &PathBuf,
+    event_name: &str,
+) {
+    if let Some(mut child) = running_child.take() {
+        if let Err(error) = child.kill() {
+            eprintln!("failed to kill previous command: {}", error);
+        }
+        let _ = child.wait();
+    }
+
+    let spawned = std::process::Command::new(&command[0])
+        .args(&command[1..])
+        .env("WATCHED_PATH", path)
+        .env("WATCHED_EVENT", event_name)
+        .group_spawn();
+
+    match spawned {
+        Ok(child) => *running_child = Some(child),
+        Err(error) => eprintln!("failed to run command: {}", error),
+    }
+}
+
+/// `notify::Watcher` isn't dyn-compatible, so this picks between the OS-native backend and
+/// `PollWatcher` (used for `--poll`) at the call site instead of behind a trait object.
+enum AnyWatcher {
+    Native(notify::RecommendedWatcher),
+    Poll(notify::PollWatcher),
+}
+
+impl AnyWatcher {
+    fn watch<P: AsRef<std::path::Path>>(
+        &mut self,
+        path: P,
+        recursive_mode: notify::RecursiveMode,
+    ) -> notify::Result<()> {
+        match self {
+            AnyWatcher::Native(watcher) => watcher.watch(path, recursive_mode),
+            AnyWatcher::Poll(watcher) => watcher.watch(path, recursive_mode),
+        }
+    }
+}
+
+/// A stack of `Gitignore` matchers, one per `.gitignore` found from `watch_path` up to the
+/// filesystem root (plus one for `--ignore` globs), each anchored at its own directory. A single shared `Gitignore` can't
+/// represent this: it strips one common root before matching, so an ancestor's anchored rule
+/// (e.g. `/build`) would wrongly apply relative to `watch_path` instead of to its own directory.
+struct IgnoreMatcher {
+    levels: Vec<Gitignore>,
+}
+
+impl IgnoreMatcher {
+    #[cfg(test)]
+    fn empty() -> Self {
+        IgnoreMatcher { levels: Vec::new() }
+    }
+
+    /// Checks `path` against each level from the most specific (closest to `watch_path`) to the
+    /// least specific, the same precedence real `.gitignore` nesting has, returning the first
+    /// definitive verdict (a more specific level can un-ignore what a parent ignored).
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        for level in &self.levels {
+            match level.matched(path, is_dir) {
+                ignore::Match::None => continue,
+                ignore::Match::Ignore(_) => return true,
+                ignore::Match::Whitelist(_) => return false,
+            }
+        }
+        false
+    }
+}
+
 #[derive(Eq,PartialEq)]
 enum Events {
     Create,
@@ -46,7 +225,13 @@ enum Events {
 struct Settings {
     watch_path: PathBuf,
     delay: Duration,
-    events: Vec<Events>
+    events: Vec<Events>,
+    command: Option<Vec<String>>,
+    poll_interval: Option<Duration>,
+    ignore_matcher: IgnoreMatcher,
+    path_filter: Option<globset::GlobSet>,
+    clear_screen: bool,
+    restart: bool
 }
 
 /// Uses the `clap` crate to generate help/usage printing as well as parse the given arguments.
@@ -77,6 +262,68 @@ fn parse_arguments() -> Settings {
             .multiple(true)
             .possible_values(&possible_event_values),
             )
+        .arg(
+            clap::Arg::with_name("exec")
+                .help("Run CMD (everything after --) on every matching event instead of printing it. $WATCHED_PATH and $WATCHED_EVENT are set on the child's environment")
+                .value_name("CMD")
+                .takes_value(true)
+                .multiple(true)
+                .min_values(1)
+                .last(true),
+        )
+        .arg(
+            clap::Arg::with_name("poll")
+                .help("Use poll-based watching instead of the OS-native backend, with an optional interval in ms (default 1000)")
+                .long("poll")
+                .value_name("MS")
+                .takes_value(true)
+                .min_values(0),
+        )
+        .arg(
+            clap::Arg::with_name("ignore")
+                .help("Ignore events whose path matches GLOB, in addition to .gitignore rules")
+                .short("i")
+                .long("ignore")
+                .value_name("GLOB")
+                .takes_value(true)
+                .multiple(true),
+        )
+        .arg(
+            clap::Arg::with_name("no-vcs-ignore")
+                .help("Don't read .gitignore files when filtering events")
+                .long("no-vcs-ignore"),
+        )
+        .arg(
+            clap::Arg::with_name("exts")
+                .help("Only report events for files with one of these comma-separated extensions, e.g. js,css,html")
+                .short("e")
+                .long("exts")
+                .value_name("EXTS")
+                .takes_value(true)
+                .use_delimiter(true)
+                .multiple(true),
+        )
+        .arg(
+            clap::Arg::with_name("filter")
+                .help("Only report events for files matching GLOB")
+                .short("f")
+                .long("filter")
+                .value_name("GLOB")
+                .takes_value(true)
+                .multiple(true),
+        )
+        .arg(
+            clap::Arg::with_name("clear")
+                .help("Clear the screen before acting on a new batch of changes")
+                .short("c")
+                .long("clear"),
+        )
+        .arg(
+            clap::Arg::with_name("restart")
+                .help("With a trailing -- CMD, kill and restart it if it's still running when a new matching event arrives")
+                .short("r")
+                .long("restart"),
+        )
         .get_matches();
 
     // Pull out the PATH argument. Fall back to the current working directory if it was not given.
@@ -109,5 +356,280 @@ fn parse_arguments() -> Settings {
         }
     }
 
-    Settings { watch_path, delay, events }
+    let command = matches
+        .values_of("exec")
+        .map(|values| values.map(String::from).collect());
+
+    // `--poll` without a value falls back to a 1s interval, matching watchexec's default.
+    let poll_interval = if matches.is_present("poll") {
+        let poll_ms = match matches.value_of("poll") {
+            Some(ms) => ms.parse().unwrap_or_else(|_| {
+                clap::Error::value_validation_auto(format!("'{}' isn't a valid poll interval", ms))
+                    .exit()
+            }),
+            None => 1000,
+        };
+        Some(Duration::from_millis(poll_ms))
+    } else {
+        None
+    };
+
+    let ignore_matcher = build_ignore_matcher(
+        &watch_path,
+        matches.values_of("ignore"),
+        !matches.is_present("no-vcs-ignore"),
+    );
+
+    let path_filter = build_path_filter(matches.values_of("exts"), matches.values_of("filter"));
+
+    let clear_screen = matches.is_present("clear");
+    let restart = matches.is_present("restart");
+
+    Settings { watch_path, delay, events, command, poll_interval, ignore_matcher, path_filter, clear_screen, restart }
+}
+
+/// Builds a `GlobSet` from `--exts`/`--filter`, or `None` if neither was given (meaning: don't
+/// filter by path at all).
+fn build_path_filter(
+    exts: Option<clap::Values>,
+    filters: Option<clap::Values>,
+) -> Option<globset::GlobSet> {
+    let mut builder = globset::GlobSetBuilder::new();
+    let mut has_patterns = false;
+
+    for ext in exts.into_iter().flatten() {
+        has_patterns = true;
+        match globset::Glob::new(&format!("*.{}", ext)) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(error) => eprintln!("warning: invalid extension '{}': {}", ext, error),
+        }
+    }
+
+    for filter in filters.into_iter().flatten() {
+        has_patterns = true;
+        match globset::Glob::new(filter) {
+            Ok(glob) => {
+                builder.add(glob);
+            }
+            Err(error) => eprintln!("warning: invalid --filter glob '{}': {}", filter, error),
+        }
+    }
+
+    if !has_patterns {
+        return None;
+    }
+
+    match builder.build() {
+        Ok(set) => Some(set),
+        Err(error) => {
+            eprintln!("warning: failed to build path filter: {}", error);
+            None
+        }
+    }
+}
+
+/// Walks from `watch_path` upward collecting `.gitignore` files (unless `use_vcs_ignore` is
+/// false), building one `Gitignore` per directory so each level's rules stay anchored to that
+/// level, then adds any `--ignore` globs as the most specific (checked-first) level.
+fn build_ignore_matcher(
+    watch_path: &PathBuf,
+    extra_globs: Option<clap::Values>,
+    use_vcs_ignore: bool,
+) -> IgnoreMatcher {
+    let mut levels = Vec::new();
+
+    if use_vcs_ignore {
+        let mut dir = Some(watch_path.as_path());
+        while let Some(d) = dir {
+            let gitignore_path = d.join(".gitignore");
+            if gitignore_path.is_file() {
+                let mut builder = GitignoreBuilder::new(d);
+                if let Some(error) = builder.add(&gitignore_path) {
+                    eprintln!("warning: {}", error);
+                }
+                match builder.build() {
+                    Ok(gitignore) => levels.push(gitignore),
+                    Err(error) => {
+                        eprintln!("warning: failed to build {}: {}", gitignore_path.display(), error)
+                    }
+                }
+            }
+            dir = d.parent();
+        }
+    }
+
+    if let Some(globs) = extra_globs {
+        let mut builder = GitignoreBuilder::new(watch_path);
+        let mut any_globs = false;
+        for glob in globs {
+            any_globs = true;
+            if let Err(error) = builder.add_line(None, glob) {
+                eprintln!("warning: invalid --ignore glob '{}': {}", glob, error);
+            }
+        }
+        if any_globs {
+            match builder.build() {
+                Ok(gitignore) => levels.insert(0, gitignore),
+                Err(error) => eprintln!("warning: failed to build --ignore matcher: {}", error),
+            }
+        }
+    }
+
+    IgnoreMatcher { levels }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let id = COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!(
+            "rustgbg-cli-quickstart-test-{}-{}-{}",
+            std::process::id(),
+            name,
+            id
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn settings_with_ignore(watch_path: PathBuf, ignore_matcher: IgnoreMatcher) -> Settings {
+        Settings {
+            watch_path,
+            delay: Duration::from_millis(100),
+            events: Vec::new(),
+            command: None,
+            poll_interval: None,
+            ignore_matcher,
+            path_filter: None,
+            clear_screen: false,
+            restart: false,
+        }
+    }
+
+    #[test]
+    fn gitignore_file_is_respected() {
+        let dir = temp_dir("gitignore");
+        std::fs::write(dir.join(".gitignore"), "*.log\n").unwrap();
+
+        let matcher = build_ignore_matcher(&dir, None, true);
+        let settings = settings_with_ignore(dir.clone(), matcher);
+
+        assert!(is_ignored(&settings, &dir.join("debug.log")));
+        assert!(!is_ignored(&settings, &dir.join("main.rs")));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn no_vcs_ignore_disables_gitignore_file() {
+        let dir = temp_dir("no-vcs-ignore");
+        std::fs::write(dir.join(".gitignore"), "*.log\n").unwrap();
+
+        let matcher = build_ignore_matcher(&dir, None, false);
+        let settings = settings_with_ignore(dir.clone(), matcher);
+
+        assert!(!is_ignored(&settings, &dir.join("debug.log")));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn extra_ignore_glob_is_applied_without_vcs_ignore() {
+        let dir = temp_dir("extra-glob");
+        let matches = clap::App::new("test")
+            .arg(
+                clap::Arg::with_name("ignore")
+                    .long("ignore")
+                    .takes_value(true)
+                    .multiple(true),
+            )
+            .get_matches_from(vec!["test", "--ignore", "*.tmp"]);
+
+        let matcher = build_ignore_matcher(&dir, matches.values_of("ignore"), false);
+        let settings = settings_with_ignore(dir.clone(), matcher);
+
+        assert!(is_ignored(&settings, &dir.join("scratch.tmp")));
+        assert!(!is_ignored(&settings, &dir.join("main.rs")));
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn ancestor_gitignore_rule_is_anchored_to_its_own_directory() {
+        let root = temp_dir("ancestor-root");
+        std::fs::write(root.join(".gitignore"), "/build\n").unwrap();
+        let watch_path = root.join("src");
+        std::fs::create_dir_all(&watch_path).unwrap();
+
+        let matcher = build_ignore_matcher(&watch_path, None, true);
+        let settings = settings_with_ignore(watch_path.clone(), matcher);
+
+        // `/build` in the root .gitignore is anchored to `root`, not to `root/src`.
+        assert!(!is_ignored(&settings, &watch_path.join("build/output")));
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    fn settings_for(events: Vec<Events>, path_filter: Option<globset::GlobSet>) -> Settings {
+        Settings {
+            watch_path: PathBuf::new(),
+            delay: Duration::from_millis(100),
+            events,
+            command: None,
+            poll_interval: None,
+            ignore_matcher: IgnoreMatcher::empty(),
+            path_filter,
+            clear_screen: false,
+            restart: false,
+        }
+    }
+
+    fn exts_filter(exts: &str) -> Option<globset::GlobSet> {
+        let matches = clap::App::new("test")
+            .arg(
+                clap::Arg::with_name("exts")
+                    .long("exts")
+                    .takes_value(true)
+                    .use_delimiter(true)
+                    .multiple(true),
+            )
+            .get_matches_from(vec!["test", "--exts", exts]);
+        build_path_filter(matches.values_of("exts"), None)
+    }
+
+    #[test]
+    fn build_path_filter_returns_none_without_patterns() {
+        assert!(build_path_filter(None, None).is_none());
+    }
+
+    #[test]
+    fn build_path_filter_matches_given_extensions() {
+        let filter = exts_filter("js,css").unwrap();
+
+        assert!(filter.is_match(Path::new("app.js")));
+        assert!(filter.is_match(Path::new("style.css")));
+        assert!(!filter.is_match(Path::new("index.html")));
+    }
+
+    #[test]
+    fn should_act_requires_matching_event_kind() {
+        let settings = settings_for(vec![Events::Write], None);
+
+        assert!(should_act(&settings, Path::new("a.rs"), Events::Write));
+        assert!(!should_act(&settings, Path::new("a.rs"), Events::Create));
+    }
+
+    #[test]
+    fn should_act_respects_path_filter() {
+        let settings = settings_for(vec![Events::Write], exts_filter("rs"));
+
+        assert!(should_act(&settings, Path::new("main.rs"), Events::Write));
+        assert!(!should_act(&settings, Path::new("main.txt"), Events::Write));
+    }
 }